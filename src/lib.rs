@@ -1,8 +1,11 @@
 //! A simple global allocator which hooks into `libc`.
 //! Useful when linking `no_std` + `alloc` code into existing embedded C code.
 //!
-//! Uses `posix_memalign` for allocations, `realloc` for reallocations, and
-//! `free` for deallocations.
+//! Uses `posix_memalign` for over-aligned allocations, `realloc` for
+//! reallocations, and `free` for deallocations. The `aligned_alloc` and
+//! `memalign` Cargo features switch that over-aligned primitive to C11's
+//! `aligned_alloc` or the older `memalign`, for libcs that don't provide
+//! `posix_memalign`.
 //!
 //! ## Example
 //!
@@ -12,13 +15,33 @@
 //! #[global_allocator]
 //! static ALLOCATOR: LibcAlloc = LibcAlloc;
 //! ```
+//!
+//! With the `allocator_api` feature enabled, `LibcAlloc` also implements the
+//! (nightly-only) `core::alloc::Allocator` trait on unix and
+//! `target_family = "none"` targets (not on Windows), so it can back
+//! individual containers such as `Box::new_in` or `Vec::with_capacity_in`
+//! instead of (or in addition to) serving as the `#[global_allocator]`.
+//!
+//! With the `oom_hook` feature enabled, [`set_alloc_error_callback`] lets
+//! embedded users register a callback that's invoked with the failing
+//! `Layout` whenever libc returns null, which is otherwise invisible to Rust.
+//! The Windows CRT implementation never invokes the callback.
 
 #![no_std]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 
 use core::alloc::{GlobalAlloc, Layout};
 use core::ffi::c_void;
 use core::ptr;
 
+#[cfg(feature = "allocator_api")]
+use core::alloc::{AllocError, Allocator};
+#[cfg(feature = "allocator_api")]
+use core::ptr::NonNull;
+
+#[cfg(feature = "oom_hook")]
+use core::sync::atomic::{AtomicPtr, Ordering};
+
 // The minimum alignment guaranteed by the architecture. This value is used to
 // add fast paths for low alignment values.
 #[cfg(any(
@@ -63,34 +86,109 @@ mod win_crt;
 /// Global Allocator which hooks into libc to allocate / free memory.
 pub struct LibcAlloc;
 
+#[cfg(feature = "oom_hook")]
+static ALLOC_ERROR_CALLBACK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Registers a callback to be invoked with the failing [`Layout`] whenever
+/// `LibcAlloc` fails to obtain memory from libc, before the null pointer is
+/// returned to Rust's allocation machinery. Only the unix and
+/// `target_family = "none"` implementation reports failures; on Windows,
+/// a null return from the CRT does not invoke the callback.
+///
+/// This is useful on embedded targets, where a failed allocation would
+/// otherwise be a silent null pointer with no Rust-visible signal: the
+/// callback can log to a UART, trip a watchdog, or record the failing
+/// size/alignment for postmortem.
+#[cfg(feature = "oom_hook")]
+pub fn set_alloc_error_callback(callback: fn(Layout)) {
+    ALLOC_ERROR_CALLBACK.store(callback as *mut (), Ordering::SeqCst);
+}
+
+#[cfg(feature = "oom_hook")]
+#[inline]
+fn report_alloc_error(layout: Layout) {
+    let callback = ALLOC_ERROR_CALLBACK.load(Ordering::SeqCst);
+    if !callback.is_null() {
+        let callback: fn(Layout) = unsafe { core::mem::transmute(callback) };
+        callback(layout);
+    }
+}
+
+// The over-aligned allocation primitive used once `alloc`'s malloc fast path
+// doesn't apply. Which symbol this binds to is chosen by Cargo feature, so
+// users linking against a libc that only exports one of these can still use
+// the crate without forking it.
 #[cfg(any(target_family = "unix", target_family = "none"))]
-unsafe impl GlobalAlloc for LibcAlloc {
-    #[inline]
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+#[inline]
+unsafe fn alloc_overaligned(layout: Layout) -> *mut u8 {
+    let align = layout.align().max(core::mem::size_of::<usize>());
+
+    #[cfg(feature = "aligned_alloc")]
+    {
+        // C11 requires that size be a multiple of align.
+        let size = layout.size().div_ceil(align) * align;
+        libc::aligned_alloc(align, size) as *mut u8
+    }
+
+    #[cfg(all(feature = "memalign", not(feature = "aligned_alloc")))]
+    {
+        libc::memalign(align, layout.size()) as *mut u8
+    }
+
+    #[cfg(not(any(feature = "aligned_alloc", feature = "memalign")))]
+    {
         let mut ptr = ptr::null_mut();
-        let ret = libc::posix_memalign(
-            &mut ptr,
-            layout.align().max(core::mem::size_of::<usize>()),
-            layout.size(),
-        );
+        let ret = libc::posix_memalign(&mut ptr, align, layout.size());
         if ret == 0 {
             ptr as *mut u8
         } else {
             ptr::null_mut()
         }
     }
+}
 
+#[cfg(any(target_family = "unix", target_family = "none"))]
+unsafe impl GlobalAlloc for LibcAlloc {
     #[inline]
-    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        // Unfortunately, calloc doesn't make any alignment guarantees, so the memory
-        // has to be manually zeroed-out.
-        let ptr = self.alloc(layout);
-        if !ptr.is_null() {
-            ptr::write_bytes(ptr, 0, layout.size());
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // malloc is already guaranteed to return memory aligned to MIN_ALIGN, so
+        // skip the more expensive over-aligned allocation primitive whenever
+        // that's sufficient.
+        let ptr = if layout.align() <= MIN_ALIGN && layout.align() <= layout.size() {
+            libc::malloc(layout.size()) as *mut u8
+        } else {
+            alloc_overaligned(layout)
+        };
+        #[cfg(feature = "oom_hook")]
+        if ptr.is_null() {
+            report_alloc_error(layout);
         }
         ptr
     }
 
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        // calloc is already guaranteed to return memory aligned to MIN_ALIGN, and
+        // may hand back fresh, lazily-zeroed kernel pages instead of actually
+        // touching every byte, so prefer it whenever alignment allows.
+        if layout.align() <= MIN_ALIGN && layout.align() <= layout.size() {
+            let ptr = libc::calloc(layout.size(), 1) as *mut u8;
+            #[cfg(feature = "oom_hook")]
+            if ptr.is_null() {
+                report_alloc_error(layout);
+            }
+            ptr
+        } else {
+            // Unfortunately, calloc makes no alignment guarantees beyond MIN_ALIGN, so
+            // the memory has to be manually zeroed-out.
+            let ptr = self.alloc(layout);
+            if !ptr.is_null() {
+                ptr::write_bytes(ptr, 0, layout.size());
+            }
+            ptr
+        }
+    }
+
     #[inline]
     unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
         libc::free(ptr as *mut c_void);
@@ -100,13 +198,123 @@ unsafe impl GlobalAlloc for LibcAlloc {
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
         // check layout, and if it requires stricter alignment, fallback to alloc + copy + free.
         if layout.align() <= MIN_ALIGN && layout.align() <= new_size {
-            libc::realloc(ptr as *mut c_void, new_size) as *mut u8
+            let new_ptr = libc::realloc(ptr as *mut c_void, new_size) as *mut u8;
+            #[cfg(feature = "oom_hook")]
+            if new_ptr.is_null() {
+                report_alloc_error(Layout::from_size_align_unchecked(new_size, layout.align()));
+            }
+            new_ptr
         } else {
             realloc_fallback(self, ptr, layout, new_size)
         }
     }
 }
 
+#[cfg(all(
+    feature = "allocator_api",
+    any(target_family = "unix", target_family = "none")
+))]
+unsafe impl Allocator for LibcAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            let dangling = unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+            return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+        }
+        let ptr = unsafe { GlobalAlloc::alloc(self, layout) };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            let dangling = unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+            return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+        }
+        let ptr = unsafe { GlobalAlloc::alloc_zeroed(self, layout) };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            GlobalAlloc::dealloc(self, ptr.as_ptr(), layout);
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(new_layout.size() >= old_layout.size());
+        if old_layout.size() == 0 {
+            return self.allocate(new_layout);
+        }
+        let raw_ptr = if old_layout.align() == new_layout.align() {
+            // GlobalAlloc::realloc already goes through the oom_hook-reporting
+            // libc::realloc fast path (or the hooked alloc+copy+free fallback)
+            // for same-alignment growth.
+            GlobalAlloc::realloc(self, ptr.as_ptr(), old_layout, new_layout.size())
+        } else {
+            let new_ptr = GlobalAlloc::alloc(self, new_layout);
+            if !new_ptr.is_null() {
+                ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr, old_layout.size());
+                GlobalAlloc::dealloc(self, ptr.as_ptr(), old_layout);
+            }
+            new_ptr
+        };
+        let new_ptr = NonNull::new(raw_ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(new_ptr, new_layout.size()))
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        let new_ptr = self.grow(ptr, old_layout, new_layout)?;
+        ptr::write_bytes(
+            new_ptr.cast::<u8>().as_ptr().add(old_layout.size()),
+            0,
+            new_layout.size() - old_layout.size(),
+        );
+        Ok(new_ptr)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(new_layout.size() <= old_layout.size());
+        if new_layout.size() == 0 {
+            if old_layout.size() != 0 {
+                GlobalAlloc::dealloc(self, ptr.as_ptr(), old_layout);
+            }
+            let dangling = NonNull::new_unchecked(new_layout.align() as *mut u8);
+            return Ok(NonNull::slice_from_raw_parts(dangling, 0));
+        }
+        let raw_ptr = if old_layout.align() == new_layout.align() {
+            // GlobalAlloc::realloc already goes through the oom_hook-reporting
+            // libc::realloc fast path (or the hooked alloc+copy+free fallback)
+            // for same-alignment shrinking.
+            GlobalAlloc::realloc(self, ptr.as_ptr(), old_layout, new_layout.size())
+        } else {
+            let new_ptr = GlobalAlloc::alloc(self, new_layout);
+            if !new_ptr.is_null() {
+                ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr, new_layout.size());
+                GlobalAlloc::dealloc(self, ptr.as_ptr(), old_layout);
+            }
+            new_ptr
+        };
+        let new_ptr = NonNull::new(raw_ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(new_ptr, new_layout.size()))
+    }
+}
+
 #[cfg(any(target_family = "unix", target_family = "none"))]
 pub unsafe fn realloc_fallback(
     alloc: &LibcAlloc,
@@ -0,0 +1,29 @@
+use core::ffi::c_void;
+
+#[cfg(all(feature = "aligned_alloc", feature = "memalign"))]
+compile_error!("features \"aligned_alloc\" and \"memalign\" are mutually exclusive");
+
+extern "C" {
+    pub fn malloc(size: usize) -> *mut c_void;
+    pub fn calloc(nmemb: usize, size: usize) -> *mut c_void;
+    pub fn free(p: *mut c_void);
+    pub fn realloc(p: *mut c_void, size: usize) -> *mut c_void;
+}
+
+// The over-aligned allocation primitive is pluggable: not every libc a user
+// links against exports `posix_memalign` (or exports a faster alternative),
+// so the symbol bound here is picked by Cargo feature.
+#[cfg(not(any(feature = "aligned_alloc", feature = "memalign")))]
+extern "C" {
+    pub fn posix_memalign(memptr: &mut *mut c_void, align: usize, size: usize) -> i32;
+}
+
+#[cfg(feature = "aligned_alloc")]
+extern "C" {
+    pub fn aligned_alloc(align: usize, size: usize) -> *mut c_void;
+}
+
+#[cfg(all(feature = "memalign", not(feature = "aligned_alloc")))]
+extern "C" {
+    pub fn memalign(align: usize, size: usize) -> *mut c_void;
+}